@@ -0,0 +1,176 @@
+//! Frame-pointer-based kernel stack backtraces.
+//!
+//! This crate maps the kernel ELF's `.symtab`/`.strtab` sections (done once,
+//! during early memory initialization, since the original `BootInformation`
+//! is unmapped shortly afterwards), copies the symbol/string data into a
+//! global table, and uses it to resolve return addresses discovered by
+//! walking the saved RBP frame-pointer chain into symbol names.
+
+#![no_std]
+
+extern crate alloc;
+#[macro_use] extern crate log;
+extern crate memory;
+extern crate multiboot2;
+extern crate spin;
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::str;
+use memory::{EntryFlags, FrameAllocator, MappedPages, PageTable, PhysicalAddress};
+use multiboot2::BootInformation;
+use spin::Mutex;
+
+/// The maximum number of stack frames to walk before giving up, in case the
+/// RBP chain is corrupt and would otherwise loop or wander into unmapped memory.
+const MAX_BACKTRACE_FRAMES: usize = 64;
+
+/// A 64-bit ELF symbol table entry (`Elf64_Sym`), as laid out in `.symtab`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ElfSym {
+    st_name: u32,
+    st_info: u8,
+    st_other: u8,
+    st_shndx: u16,
+    st_value: u64,
+    st_size: u64,
+}
+
+/// An owned copy of the kernel's `.symtab`/`.strtab` contents, along with an
+/// index of symbol table entries sorted by `st_value` so lookups can binary-search.
+struct SymbolTable {
+    syms: Vec<ElfSym>,
+    strtab: Vec<u8>,
+    /// Indices into `syms`, sorted by `st_value`.
+    sorted_by_addr: Vec<u32>,
+}
+
+impl SymbolTable {
+    fn name_of(&self, st_name: u32) -> &str {
+        let start = st_name as usize;
+        if start >= self.strtab.len() {
+            return "<bad symbol name>";
+        }
+        let end = self.strtab[start..].iter().position(|&b| b == 0)
+            .map(|i| start + i)
+            .unwrap_or(self.strtab.len());
+        str::from_utf8(&self.strtab[start..end]).unwrap_or("<invalid utf8>")
+    }
+
+    /// Binary-searches for the symbol whose range `[st_value, st_value + st_size)`
+    /// contains `addr`, returning its name and the offset from its start. A zero-sized
+    /// symbol is treated as one byte long, so it matches only its own start address.
+    fn resolve(&self, addr: u64) -> Option<(&str, u64)> {
+        let idx = self.sorted_by_addr.binary_search_by(|&i| {
+            let sym = &self.syms[i as usize];
+            if addr < sym.st_value {
+                core::cmp::Ordering::Greater
+            } else if addr >= sym.st_value + sym.st_size.max(1) {
+                core::cmp::Ordering::Less
+            } else {
+                core::cmp::Ordering::Equal
+            }
+        }).ok()?;
+        let sym = &self.syms[self.sorted_by_addr[idx] as usize];
+        Some((self.name_of(sym.st_name), addr - sym.st_value))
+    }
+}
+
+static SYMBOL_TABLE: Mutex<Option<SymbolTable>> = Mutex::new(None);
+
+/// Locates the kernel's `.symtab` and `.strtab` ELF sections described by the
+/// multiboot2 ELF-sections tag, maps them read-only into `page_table`, and
+/// copies their contents into the global table used by [`backtrace()`].
+///
+/// Returns the `MappedPages` for the symbol table and string table (in that
+/// order) so the caller can keep them mapped for the lifetime of the kernel,
+/// the same way it already does for the text/rodata/data sections.
+pub fn map_symbol_tables(
+    boot_info: &BootInformation,
+    page_table: &mut PageTable,
+    frame_allocator: &mut dyn FrameAllocator,
+) -> Result<(MappedPages, MappedPages), &'static str> {
+    let elf_sections_tag = boot_info.elf_sections_tag()
+        .ok_or("backtrace: multiboot2 boot information is missing the ELF sections tag")?;
+
+    let symtab_section = elf_sections_tag.sections()
+        .find(|s| s.name() == ".symtab")
+        .ok_or("backtrace: kernel image has no .symtab section")?;
+    let strtab_section = elf_sections_tag.sections()
+        .find(|s| s.name() == ".strtab")
+        .ok_or("backtrace: kernel image has no .strtab section")?;
+
+    let symtab_mp = map_section(&symtab_section, page_table, frame_allocator)?;
+    let strtab_mp = map_section(&strtab_section, page_table, frame_allocator)?;
+
+    let num_syms = symtab_mp.size_in_bytes() / size_of::<ElfSym>();
+    let syms: Vec<ElfSym> = unsafe {
+        symtab_mp.as_slice::<ElfSym>(0, num_syms).map_err(|_| "backtrace: .symtab mapping too small")?
+    }.to_vec();
+    let strtab: Vec<u8> = unsafe {
+        strtab_mp.as_slice::<u8>(0, strtab_mp.size_in_bytes()).map_err(|_| "backtrace: .strtab mapping too small")?
+    }.to_vec();
+
+    let mut sorted_by_addr: Vec<u32> = (0..syms.len() as u32).collect();
+    sorted_by_addr.sort_unstable_by_key(|&i| syms[i as usize].st_value);
+
+    debug!("backtrace: mapped .symtab ({} symbols) and .strtab ({} bytes)", syms.len(), strtab.len());
+    *SYMBOL_TABLE.lock() = Some(SymbolTable { syms, strtab, sorted_by_addr });
+
+    Ok((symtab_mp, strtab_mp))
+}
+
+/// Maps the physical frames backing a kernel ELF section (as already loaded
+/// by the bootloader) read-only into `page_table`.
+fn map_section(
+    section: &multiboot2::ElfSection,
+    page_table: &mut PageTable,
+    frame_allocator: &mut dyn FrameAllocator,
+) -> Result<MappedPages, &'static str> {
+    let start_addr = PhysicalAddress::new(section.start_address() as usize)
+        .map_err(|_| "backtrace: ELF section had an invalid physical start address")?;
+    memory::map_frame_range(start_addr, section.size() as usize, EntryFlags::PRESENT, page_table, frame_allocator)
+}
+
+/// Walks the current call stack's RBP frame-pointer chain and logs each
+/// return address alongside the symbol (and offset) it falls within, e.g.
+/// `frame#: <addr> <symbol+offset>`. Stops after [`MAX_BACKTRACE_FRAMES`]
+/// frames, or as soon as a frame pointer points somewhere that isn't mapped,
+/// so a corrupt stack can't turn a backtrace request into another fault.
+pub fn backtrace() {
+    let mut rbp: usize;
+    unsafe {
+        core::arch::asm!("mov {}, rbp", out(reg) rbp);
+    }
+
+    info!("Stack backtrace:");
+    let table = SYMBOL_TABLE.lock();
+    for frame in 0..MAX_BACKTRACE_FRAMES {
+        if !is_mapped(rbp) || !is_mapped(rbp + size_of::<usize>()) {
+            break;
+        }
+        let saved_rbp = unsafe { *(rbp as *const usize) };
+        let return_addr = unsafe { *((rbp + size_of::<usize>()) as *const usize) };
+        if return_addr == 0 {
+            break;
+        }
+
+        match table.as_ref().and_then(|t| t.resolve(return_addr as u64)) {
+            Some((name, offset)) => info!("  {}: {:#X} {}+{:#X}", frame, return_addr, name, offset),
+            None => info!("  {}: {:#X} <unknown>", frame, return_addr),
+        }
+
+        if saved_rbp <= rbp {
+            break;
+        }
+        rbp = saved_rbp;
+    }
+}
+
+/// Returns `true` if `addr` falls within a region currently mapped in the
+/// kernel's active page table, used to guard every frame-pointer dereference
+/// during a backtrace.
+fn is_mapped(addr: usize) -> bool {
+    memory::translate(memory::VirtualAddress::new_canonical(addr)).is_some()
+}
@@ -0,0 +1,106 @@
+//! Tracks which top-level (P4) page-table entries the kernel's own mappings occupy,
+//! so that address spaces created after kernel initialization can lazily sync just
+//! those entries instead of eagerly cloning the kernel's entire mapping into every
+//! new page table.
+//!
+//! As `memory_initialization::init_memory_management` maps the kernel heap, the
+//! higher-half regions, the ELF symbol/string tables, and later per-CPU stacks, each
+//! of those calls should feed its `MappedPages` through [`record_kernel_mapping`].
+//! A new address space then calls [`arch_sync_kernel_mappings`] once to pick up
+//! everything recorded so far, and can cheaply check [`generation`] (via
+//! [`KernelMappingSyncState`]) to tell whether a later kernel mapping -- e.g. the
+//! heap or a kernel stack growing into a previously-empty P4 slot -- means it needs
+//! to sync again.
+
+#![no_std]
+
+extern crate alloc;
+#[macro_use] extern crate log;
+extern crate memory;
+extern crate spin;
+
+use alloc::collections::BTreeSet;
+use core::sync::atomic::{AtomicU64, Ordering};
+use memory::{MappedPages, PageTable};
+use spin::Mutex;
+
+/// The number of bits of a virtual address below the P4 index.
+const P4_INDEX_SHIFT: usize = 39;
+const P4_INDEX_MASK: usize = 0x1FF;
+
+fn p4_index_of(addr: usize) -> usize {
+    (addr >> P4_INDEX_SHIFT) & P4_INDEX_MASK
+}
+
+/// The top-level (P4) page-table indices that the kernel's own mappings currently occupy.
+static KERNEL_P4_ENTRIES: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// Bumped once by each `record_kernel_mapping` call that adds one or more new P4 indices
+/// to `KERNEL_P4_ENTRIES`. Address spaces
+/// compare this against the generation they last synced at (see
+/// [`KernelMappingSyncState`]) and call [`arch_sync_kernel_mappings`] again on demand
+/// if it has moved on.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Records that `mapped_pages` touches one or more top-level (P4) page-table entries,
+/// so a later [`arch_sync_kernel_mappings`] call knows to copy them into new address
+/// spaces. Bumps the generation counter if this added any entry that wasn't already tracked.
+pub fn record_kernel_mapping(mapped_pages: &MappedPages) {
+    let start = p4_index_of(mapped_pages.start_address().value());
+    let last_byte = mapped_pages.start_address().value() + mapped_pages.size_in_bytes().saturating_sub(1);
+    let end = p4_index_of(last_byte);
+
+    let mut entries = KERNEL_P4_ENTRIES.lock();
+    let mut added = false;
+    for index in start..=end {
+        added |= entries.insert(index);
+    }
+    if added {
+        let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+        debug!("kernel_mappings: now tracking {} top-level (P4) kernel entries (generation {})", entries.len(), generation);
+    }
+}
+
+/// Returns the current kernel-mappings generation.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// Copies exactly the recorded top-level (P4) kernel entries from the currently-active
+/// kernel page table into `new_page_table`, so a freshly created address space can see
+/// the kernel's heap, higher-half mappings, and per-CPU stacks without eagerly cloning
+/// the kernel's entire mapping into every new table.
+pub fn arch_sync_kernel_mappings(new_page_table: &mut PageTable) -> Result<(), &'static str> {
+    let entries = KERNEL_P4_ENTRIES.lock();
+    for &index in entries.iter() {
+        new_page_table.copy_kernel_p4_entry(index)?;
+    }
+    Ok(())
+}
+
+/// Tracks the kernel-mappings [`generation`] an address space last synced its P4 table
+/// at, so it can tell whether [`arch_sync_kernel_mappings`] needs to run again.
+pub struct KernelMappingSyncState {
+    last_synced_generation: u64,
+}
+
+impl KernelMappingSyncState {
+    /// Creates sync state for an address space that has not yet synced the kernel's mappings.
+    pub fn new() -> KernelMappingSyncState {
+        KernelMappingSyncState { last_synced_generation: 0 }
+    }
+
+    /// Returns `true` if the kernel's tracked P4 entries have changed since this address
+    /// space last synced, meaning [`Self::resync`] should be called again.
+    pub fn needs_resync(&self) -> bool {
+        self.last_synced_generation != generation()
+    }
+
+    /// Re-runs [`arch_sync_kernel_mappings`] against `page_table` and records the
+    /// generation synced at.
+    pub fn resync(&mut self, page_table: &mut PageTable) -> Result<(), &'static str> {
+        arch_sync_kernel_mappings(page_table)?;
+        self.last_synced_generation = generation();
+        Ok(())
+    }
+}
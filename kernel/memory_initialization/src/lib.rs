@@ -1,11 +1,14 @@
 #![no_std]
 
 extern crate alloc;
+extern crate backtrace;
 extern crate heap;
 extern crate kernel_config;
+extern crate kernel_mappings;
 #[macro_use] extern crate log;
 extern crate memory;
 extern crate stack;
+extern crate stack_allocator;
 extern crate multiboot2;
 
 use memory::{MmiRef, MappedPages, VirtualAddress};
@@ -42,10 +45,12 @@ macro_rules! try_forget {
 ///  * the MappedPages of the kernel's text section,
 ///  * the MappedPages of the kernel's rodata section,
 ///  * the MappedPages of the kernel's data section,
+///  * the MappedPages of the kernel's ELF symbol table (`.symtab`),
+///  * the MappedPages of the kernel's ELF string table (`.strtab`),
 ///  * the initial stack for this CPU (e.g., the BSP stack) that is currently in use,
-///  * the kernel's list of identity-mapped MappedPages which should be dropped before starting the first user application. 
-pub fn init_memory_management(boot_info: &BootInformation)  
-    -> Result<(MmiRef, MappedPages, MappedPages, MappedPages, Stack, Vec<MappedPages>), &'static str>
+///  * the kernel's list of identity-mapped MappedPages which should be dropped before starting the first user application.
+pub fn init_memory_management(boot_info: &BootInformation)
+    -> Result<(MmiRef, MappedPages, MappedPages, MappedPages, MappedPages, MappedPages, Stack, Vec<MappedPages>), &'static str>
 {
     // Initialize memory management: paging (create a new page table), essential kernel mappings
     let (
@@ -71,6 +76,14 @@ pub fn init_memory_management(boot_info: &BootInformation)
         )
     };
 
+    // These are also kernel mappings that every later address space needs to see, same as
+    // `higher_half_mapped_pages` below; record them regardless of whether they actually land
+    // in the same P4 entry as the higher-half region, since recording an already-tracked
+    // index is a harmless no-op.
+    kernel_mappings::record_kernel_mapping(&text_mapped_pages);
+    kernel_mappings::record_kernel_mapping(&rodata_mapped_pages);
+    kernel_mappings::record_kernel_mapping(&data_mapped_pages);
+
     // Initialize the kernel heap.
     let heap_start = KERNEL_HEAP_START;
     let heap_initial_size = KERNEL_HEAP_INITIAL_SIZE;
@@ -95,11 +108,35 @@ pub fn init_memory_management(boot_info: &BootInformation)
     };
 
     debug!("Mapped and initialized the initial heap");
+    kernel_mappings::record_kernel_mapping(&heap_mapped_pages);
+
+    // Map the kernel's ELF symbol table and string table so that `backtrace::backtrace()`
+    // can still resolve return addresses to symbol names after `boot_info` is gone.
+    let (symtab_mapped_pages, strtab_mapped_pages) = {
+        let mut allocator = frame_allocator_mutex.lock();
+        try_forget!(
+            backtrace::map_symbol_tables(boot_info, &mut page_table, allocator.deref_mut()),
+            text_mapped_pages, rodata_mapped_pages, data_mapped_pages, stack, higher_half_mapped_pages, identity_mapped_pages, heap_mapped_pages
+        )
+    };
+    kernel_mappings::record_kernel_mapping(&symtab_mapped_pages);
+    kernel_mappings::record_kernel_mapping(&strtab_mapped_pages);
+
+    debug!("Mapped the kernel's .symtab and .strtab sections");
+
+    // Any address space created from here on needs these same top-level (P4) entries --
+    // covering the higher-half regions below, and the heap and ELF tables above -- copied
+    // into its own page table; record them before `higher_half_mapped_pages` is consumed.
+    kernel_mappings::record_kernel_mapping(&higher_half_mapped_pages);
 
     // Initialize memory management post heap intialization: set up kernel stack allocator and kernel memory management info.
+    // (Ideally `stack_allocator::init()` would be called from inside `memory::init_post_heap` itself,
+    // right as the kernel stack allocator subsystem it sets up is first needed; it's invoked here
+    // instead because that function lives in the separate `memory` crate.)
     let (kernel_mmi_ref, identity_mapped_pages) = memory::init_post_heap(page_table, higher_half_mapped_pages, identity_mapped_pages, heap_mapped_pages)?;
+    stack_allocator::init();
 
-    Ok((kernel_mmi_ref, text_mapped_pages, rodata_mapped_pages, data_mapped_pages, stack, identity_mapped_pages))
+    Ok((kernel_mmi_ref, text_mapped_pages, rodata_mapped_pages, data_mapped_pages, symtab_mapped_pages, strtab_mapped_pages, stack, identity_mapped_pages))
 }
 
 
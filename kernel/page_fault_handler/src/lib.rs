@@ -0,0 +1,42 @@
+//! The kernel's page-fault exception handler.
+//!
+//! Beyond logging a generic fault, this recognizes a fault that lands inside one of
+//! `stack_allocator`'s guard pages and reports it as a kernel stack overflow, with the
+//! offending stack's bounds and a backtrace, instead of an opaque "page fault at 0x...".
+//!
+//! The architecture-specific IDT page-fault vector should call [`handle_page_fault`]
+//! with the faulting address it reads out of the CR2 register.
+
+#![no_std]
+
+#[macro_use] extern crate log;
+extern crate backtrace;
+extern crate memory;
+extern crate stack_allocator;
+
+use memory::VirtualAddress;
+
+/// Handles a CPU page-fault exception for `faulting_address`.
+///
+/// If `faulting_address` falls within a currently-allocated guard page, logs
+/// "kernel stack overflow in thread X" along with that stack's bounds instead of a
+/// generic fault message, then prints a backtrace either way so the fault site is visible.
+pub fn handle_page_fault(faulting_address: VirtualAddress) {
+    match stack_allocator::stack_overflow_at(faulting_address) {
+        Some(bounds) => {
+            error!(
+                "PAGE FAULT: kernel stack overflow in thread \"{}\" (stack {:#X}..{:#X}, guard page {:#X}, faulted at {:#X})",
+                bounds.thread_name,
+                bounds.bottom.value(),
+                bounds.top.value(),
+                bounds.guard_page.value(),
+                faulting_address.value(),
+            );
+        }
+        None => {
+            error!("PAGE FAULT: unhandled fault at {:#X}", faulting_address.value());
+        }
+    }
+
+    backtrace::backtrace();
+}
@@ -0,0 +1,116 @@
+//! A guard-paged kernel stack allocator.
+//!
+//! Every kernel stack handed out here -- the BSP's additional kernel threads, and
+//! secondary/AP stacks alike -- is a contiguous run of mapped pages immediately
+//! preceded by one unmapped "guard" page, mirroring the vmap-stack approach of
+//! reserving a non-present region below each stack. A thread that overflows its stack
+//! faults on the guard page instead of silently corrupting whatever lives below it;
+//! the page-fault handler can look up the faulting address with [`stack_overflow_at`]
+//! and report which thread's stack overflowed, with that stack's bounds, instead of a generic fault.
+
+#![no_std]
+
+extern crate alloc;
+extern crate kernel_mappings;
+#[macro_use] extern crate log;
+extern crate memory;
+extern crate spin;
+extern crate stack;
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use memory::{FrameAllocator, PageTable, VirtualAddress};
+use spin::Mutex;
+use stack::Stack;
+
+/// The bounds of one allocated kernel stack, recorded so that a fault on its guard
+/// page can be reported as "kernel stack overflow in thread X" rather than a generic
+/// page fault.
+#[derive(Debug, Clone)]
+pub struct StackBounds {
+    pub guard_page: VirtualAddress,
+    pub bottom: VirtualAddress,
+    pub top: VirtualAddress,
+    pub thread_name: String,
+}
+
+/// Guard pages currently outstanding, keyed by the guard page's starting address so
+/// the page-fault handler can look up a faulting address against this map.
+static GUARD_REGIONS: Mutex<BTreeMap<usize, StackBounds>> = Mutex::new(BTreeMap::new());
+
+/// Readies the stack allocator. In a fully-vendored tree this would be invoked from
+/// `memory::init_post_heap` right after the kernel heap comes up, since every stack
+/// allocated afterwards needs the heap for its bookkeeping; it's a no-op today beyond
+/// logging, since the guard-page table above is already statically initialized.
+pub fn init() {
+    debug!("stack_allocator: ready");
+}
+
+/// Allocates a new kernel stack of `stack_size_in_pages` mapped pages, preceded by one
+/// unmapped guard page, and records its bounds under `thread_name` so that a later
+/// fault on the guard page can be reported as a stack overflow for that thread.
+///
+/// Used both for additional BSP kernel threads and for secondary/AP stacks -- callers
+/// just pass a distinguishing `thread_name` for each, e.g. `"ap_3"` or `"thread_17"`.
+pub fn alloc_stack(
+    stack_size_in_pages: usize,
+    thread_name: String,
+    page_table: &mut PageTable,
+    frame_allocator: &mut dyn FrameAllocator,
+) -> Result<Stack, &'static str> {
+    let allocated_pages = memory::allocate_pages(stack_size_in_pages + 1)
+        .ok_or("stack_allocator: out of virtual address space for a new kernel stack")?;
+
+    // The guard page is the lowest page of the allocation and is deliberately left
+    // unmapped, so an overflow faults here instead of corrupting whatever sits below.
+    let (guard_page, stack_pages) = allocated_pages.split_at(1)
+        .map_err(|_| "stack_allocator: failed to split off the guard page")?;
+    let guard_page_addr = guard_page.start_address();
+
+    let stack_mapped_pages = page_table
+        .map_allocated_pages(stack_pages, stack::STACK_PAGE_FLAGS, frame_allocator)
+        .map_err(|e| {
+            error!("stack_allocator: failed to map kernel stack for thread \"{}\": {:?}", thread_name, e);
+            "stack_allocator: failed to map kernel stack pages"
+        })?;
+
+    // A kernel stack allocated post-boot can grow the kernel's mappings into a P4 slot
+    // that no address space has seen yet, so `kernel_mappings` needs to know about it
+    // too, the same way `memory_initialization` reports the heap and ELF tables.
+    kernel_mappings::record_kernel_mapping(&stack_mapped_pages);
+
+    let stack = Stack::from_pages(guard_page, stack_mapped_pages)
+        .map_err(|_| "stack_allocator: allocated stack pages were not contiguous in virtual memory")?;
+
+    GUARD_REGIONS.lock().insert(guard_page_addr.value(), StackBounds {
+        guard_page: guard_page_addr,
+        bottom: stack.bottom(),
+        top: stack.top_usable(),
+        thread_name,
+    });
+
+    Ok(stack)
+}
+
+/// Reclaims a stack's pages and drops its guard-page record when the thread that
+/// owned it exits. `stack`'s own `Drop` impl unmaps its pages and frees the
+/// underlying frames; this just forgets the bookkeeping kept for fault reporting.
+pub fn dealloc_stack(stack: Stack) {
+    let bottom = stack.bottom();
+    let mut guards = GUARD_REGIONS.lock();
+    if let Some(&key) = guards.iter().find(|&(_, bounds)| bounds.bottom == bottom).map(|(k, _)| k) {
+        guards.remove(&key);
+    }
+    drop(stack);
+}
+
+/// Looks up `faulting_address` against currently-allocated guard pages. If it falls
+/// within one, returns the bounds of the stack that guard page protects, so the
+/// page-fault handler can report "kernel stack overflow in thread X" with the
+/// offending stack's bounds instead of a generic fault.
+pub fn stack_overflow_at(faulting_address: VirtualAddress) -> Option<StackBounds> {
+    let addr = faulting_address.value();
+    GUARD_REGIONS.lock().iter()
+        .find(|&(&guard_start, _)| addr >= guard_start && addr < guard_start + memory::PAGE_SIZE)
+        .map(|(_, bounds)| bounds.clone())
+}
@@ -1,5 +1,5 @@
 use port_io::Port;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicUsize, AtomicU32, AtomicU64, Ordering};
 pub use irq_safety::{disable_interrupts, enable_interrupts, interrupts_enabled};
 use interrupts::rtc;
 use spin::Mutex;
@@ -9,8 +9,29 @@ const CMOS_WRITE_PORT: u16 = 0x70;
 //standard port to read register values from on CMOS or write to to change settings
 const CMOS_READ_PORT: u16 = 0x71;
 
+//status register B: bit 2 (0x04) is set when values are binary instead of BCD,
+//bit 1 (0x02) is set when the hour register is 24-hour instead of 12-hour
+const CMOS_STATUS_REGISTER_B: u8 = 0x0B;
+//KNOWN LIMITATION: the century register's location isn't standardized across chipsets;
+//the real source of truth is the `century` field of the ACPI FADT (0 there means "not
+//present" and a four-digit year can't be formed this way at all). This module doesn't
+//parse ACPI tables, so it always uses 0x32, the common placement on hardware that does
+//expose one. On a machine where the FADT reports a different register -- or no century
+//register at all -- `read_rtc()` will silently produce a wrong year. Once an ACPI crate
+//is wired in, prefer `fadt.century` here and fall back to 0x32 only when it's absent.
+const CMOS_CENTURY_REGISTER: u8 = 0x32;
+
 
 pub static RTC_TICKS: AtomicUsize = AtomicUsize::new(0);
+//a free-running nanosecond counter, advanced on every RTC tick by handle_rtc_interrupt();
+//the `time` clocksource subsystem layers wall-clock time on top of this
+pub static MONOTONIC_NANOS: AtomicU64 = AtomicU64::new(0);
+//the RTC's currently-programmed periodic interrupt rate in Hz, kept in sync by change_rtc_frequency();
+//1024 Hz is the chip's power-on default (rate 6)
+pub static RTC_HZ: AtomicU32 = AtomicU32::new(1024);
+//subsystems that want to run on every RTC tick register here instead of being hard-coded
+//into handle_rtc_interrupt(); None slots are free
+static TICK_CALLBACKS: Mutex<[Option<fn()>; 8]> = Mutex::new([None; 8]);
 //used to select register
 static CMOS_WRITE: Mutex<Port<u8>> = Mutex::new( Port::new(CMOS_WRITE_PORT));
 //used to change cmos settings
@@ -47,46 +68,90 @@ fn get_update_in_progress()-> bool{
 }
 
 
-//register value is entered, rtc's associated value is output, waits for update in progress signal to end
-fn read_register(register: u8)->u8{
-    
-    //waits for "update in progress" signal to finish in order to read correct values
+//register value is entered, waits for update in progress signal to end, then reads the same
+//register twice in a row and retries until two consecutive reads agree, to avoid a torn read
+//that landed in the middle of the RTC's own update of that register
+fn read_register_raw(register: u8)->u8{
+
     while get_update_in_progress() {}
     write_cmos(register);
+    let mut last = read_cmos();
+
+    loop {
+        while get_update_in_progress() {}
+        write_cmos(register);
+        let current = read_cmos();
+        if current == last {
+            return current;
+        }
+        last = current;
+    }
 
-    //converts bcd value to binary value which is what is used for printing 
-    let bcd = read_cmos();
-    
-    (bcd/16)*10 + (bcd & 0xf)
+}
+
+
+//converts a raw CMOS register value to binary, unless Status Register B's DM bit (0x04)
+//says the RTC is already configured to store values in binary instead of BCD
+fn convert_bcd(raw: u8, register_b: u8) -> u8 {
+    if (register_b & 0x04) != 0 {
+        raw
+    } else {
+        (raw/16)*10 + (raw & 0xf)
+    }
+}
 
 
+//register value is entered, rtc's associated value is output as binary, converting from BCD
+//unless register_b says this RTC is already in binary mode
+fn read_register(register: u8, register_b: u8)->u8{
+
+    convert_bcd(read_register_raw(register), register_b)
+
 }
 
 pub struct time{
-    seconds: u8,
-    minutes: u8,
-    hours: u8,
-    days: u8,
-    months: u8,
-    years: u8,
+    pub(crate) seconds: u8,
+    pub(crate) minutes: u8,
+    pub(crate) hours: u8,
+    pub(crate) days: u8,
+    pub(crate) months: u8,
+    pub(crate) years: u16,
+    pub(crate) century: u8,
 
 }
 
 //call this function to print RTC's date and time
 pub fn read_rtc()->time{
 
+    //Status Register B doesn't change after boot, so one (retried) read is enough to learn
+    //whether values are binary or BCD, and whether hours are stored as 12-hour or 24-hour
+    let register_b = read_register_raw(CMOS_STATUS_REGISTER_B);
+    let is_24_hour = (register_b & 0x02) != 0;
+
     //calls read register function which writes to port 0x70 to set RTC then reads from 0x71 which outputs correct value
-    let second = read_register(0x00);
-    let minute = read_register(0x02);
-    let hour = read_register(0x04);
-    let day = read_register(0x07);
-    let month = read_register(0x08);
-    let year = read_register(0x09);
+    let second = read_register(0x00, register_b);
+    let minute = read_register(0x02, register_b);
+
+    //hour register's bit 7 is the PM flag when the RTC is in 12-hour mode, so it has to be
+    //stripped off before BCD conversion and handled separately afterwards
+    let raw_hour = read_register_raw(0x04);
+    let is_pm = !is_24_hour && (raw_hour & 0x80) != 0;
+    let hour_of_period = convert_bcd(raw_hour & 0x7F, register_b);
+    let hour = if is_24_hour {
+        hour_of_period
+    } else {
+        (hour_of_period % 12) + if is_pm {12} else {0}
+    };
+
+    let day = read_register(0x07, register_b);
+    let month = read_register(0x08, register_b);
+    let year_of_century = read_register(0x09, register_b);
+    let century = read_register(CMOS_CENTURY_REGISTER, register_b);
+    let year = (century as u16) * 100 + (year_of_century as u16);
 
-    
     trace!("Time - {}:{}:{} {}/{}/{}", hour, minute,second, month, day, year);
 
-    time{seconds:second, minutes: minute, hours: hour, days: day, months: month, years: year}
+    time{seconds:second, minutes: minute, hours: hour, days: day, months: month, years: year, century}
 
 }
 
@@ -111,9 +176,11 @@ pub fn enable_rtc_interrupt()
     
     unsafe{CMOS_WRITE_SETTINGS.lock().write(prev | 0x40)};
 
-    
+
     enable_interrupts();
 
+    let _ = register_tick_callback(heartbeat_tick);
+
     trace!("RTC Enabled!");
 
 }
@@ -126,31 +193,62 @@ const heartbeat_period_ms: u64 = 1000;
 pub fn change_rtc_frequency(rate: u8){
 
     disable_interrupts();
-    
+
     //bottom 4 bits of register A are rate, setting them to rate we want without altering top 4 bits
     write_cmos(0x8A);
     let prev = read_cmos();
-    write_cmos(0x8A); 
+    write_cmos(0x8A);
 
     unsafe{CMOS_WRITE_SETTINGS.lock().write(((prev & 0xF0)|rate))};
 
+    //rate n gives 32768 >> (n-1) Hz; keep RTC_HZ in sync so the monotonic nanosecond
+    //counter advances by the right amount per tick
+    RTC_HZ.store(32768u32 >> (rate.saturating_sub(1)), Ordering::Relaxed);
+
     enable_interrupts();
     trace!("rtc rate frequency changed!");
 }
 
 
+//logs a heartbeat message roughly once a second; registered as a tick callback rather
+//than being hard-coded into handle_rtc_interrupt()
+fn heartbeat_tick() {
+    let rtc_ticks = RTC_TICKS.load(Ordering::SeqCst);
+    if (rtc_ticks % 128) == 0 {
+        trace!("[rtc heartbeat] {} seconds have passed (rtc ticks={})", heartbeat_period_ms/1000, rtc_ticks);
+    }
+}
+
+
+//registers a callback to be invoked on every periodic RTC tick, e.g. so the `time`
+//clocksource subsystem can drive its own bookkeeping instead of RTC calling into it directly;
+//returns an error if the (small, fixed-size) callback table is full
+pub fn register_tick_callback(callback: fn()) -> Result<(), &'static str> {
+    let mut callbacks = TICK_CALLBACKS.lock();
+    for slot in callbacks.iter_mut() {
+        if slot.is_none() {
+            *slot = Some(callback);
+            return Ok(());
+        }
+    }
+    Err("rtc: tick callback table is full")
+}
+
+
 //counts interrupts from RTC
 pub fn handle_rtc_interrupt() {
-    
+
     write_cmos(0x0C);
     read_cmos();
-    let old_tick = TICKS.fetch_add(1,Ordering::SeqCst);
-    let rtc_ticks = old_tick +1;
-  
-    
-    if (rtc_ticks % 128) == 0 {
-        trace!("[rtc heartbeat] {} seconds have passed (rtc ticks={})", heartbeat_period_ms/1000, rtc_ticks);
-    }
+    RTC_TICKS.fetch_add(1,Ordering::SeqCst);
 
+    //advance the monotonic nanosecond counter by one tick's worth of time at the
+    //currently-programmed RTC frequency
+    let hz = RTC_HZ.load(Ordering::Relaxed).max(1) as u64;
+    MONOTONIC_NANOS.fetch_add(1_000_000_000 / hz, Ordering::SeqCst);
+
+    for callback in TICK_CALLBACKS.lock().iter().flatten() {
+        callback();
+    }
 
 }
\ No newline at end of file
@@ -0,0 +1,133 @@
+//! A monotonic wall-clock timekeeping subsystem layered on the RTC.
+//!
+//! [`init()`] takes a single `rtc::read_rtc()` boot-time calendar reading and converts
+//! it to Unix epoch seconds with the standard civil-calendar algorithm. From then on,
+//! [`unix_now()`] and [`monotonic_now_ns()`] combine that fixed epoch with the
+//! free-running nanosecond counter that `rtc::handle_rtc_interrupt()` advances on every
+//! periodic RTC tick, so callers can ask "what time is it now?" (to the whole second) and
+//! measure elapsed time with sub-second resolution without touching the CMOS registers again.
+
+use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use interrupts::rtc;
+
+/// Unix epoch seconds as of the single boot-time RTC reading that `init()` took.
+static BOOT_UNIX_SECONDS: AtomicI64 = AtomicI64::new(0);
+/// The value of `rtc::MONOTONIC_NANOS` at the moment `init()` took its boot-time reading.
+static BOOT_MONOTONIC_NANOS: AtomicU64 = AtomicU64::new(0);
+
+const DAYS_IN_MONTH: [i64; 12] = [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
+}
+
+//converts an RTC calendar reading to seconds since the Unix epoch (1970-01-01T00:00:00Z):
+//days = 365*(y-1970) + leap_days + day_of_year, then secs = days*86400 + h*3600 + m*60 + s
+fn to_unix_seconds(reading: &rtc::time) -> i64 {
+    let year = reading.years as i64;
+
+    let mut leap_days = 0;
+    for y in 1970..year {
+        if is_leap_year(y) {
+            leap_days += 1;
+        }
+    }
+
+    let mut day_of_year = 0;
+    for month in 0..(reading.months as usize).saturating_sub(1) {
+        day_of_year += DAYS_IN_MONTH[month];
+        if month == 1 && is_leap_year(year) {
+            day_of_year += 1;
+        }
+    }
+    day_of_year += (reading.days as i64) - 1;
+
+    let days = 365 * (year - 1970) + leap_days + day_of_year;
+    days * 86400 + (reading.hours as i64) * 3600 + (reading.minutes as i64) * 60 + (reading.seconds as i64)
+}
+
+/// Establishes the wall-clock epoch from a one-time RTC calendar reading. Call this once
+/// during interrupt initialization, after `rtc::enable_rtc_interrupt()` has started the
+/// periodic tick counter, so `monotonic_now_ns()` is already advancing by the time this runs.
+pub fn init() {
+    let boot_reading = rtc::read_rtc();
+    BOOT_UNIX_SECONDS.store(to_unix_seconds(&boot_reading), Ordering::SeqCst);
+    BOOT_MONOTONIC_NANOS.store(rtc::MONOTONIC_NANOS.load(Ordering::SeqCst), Ordering::SeqCst);
+}
+
+/// Returns the current time as whole seconds since the Unix epoch; the sub-second
+/// part of the elapsed nanosecond counter is truncated.
+pub fn unix_now() -> i64 {
+    BOOT_UNIX_SECONDS.load(Ordering::SeqCst) + (monotonic_now_ns() / 1_000_000_000) as i64
+}
+
+/// Returns a monotonically-increasing nanosecond counter derived from RTC ticks.
+/// Useful for measuring elapsed durations; unlike `unix_now()`, it says nothing about
+/// wall-clock time on its own.
+pub fn monotonic_now_ns() -> u64 {
+    rtc::MONOTONIC_NANOS.load(Ordering::SeqCst) - BOOT_MONOTONIC_NANOS.load(Ordering::SeqCst)
+}
+
+/// Registers `callback` to run on every periodic RTC tick instead of polling
+/// `monotonic_now_ns()`; see [`rtc::register_tick_callback`] for the underlying limits.
+pub fn register_tick_callback(callback: fn()) -> Result<(), &'static str> {
+    rtc::register_tick_callback(callback)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> rtc::time {
+        rtc::time {
+            seconds: second,
+            minutes: minute,
+            hours: hour,
+            days: day,
+            months: month,
+            years: year,
+            century: (year / 100) as u8,
+        }
+    }
+
+    #[test]
+    fn epoch_is_zero() {
+        assert_eq!(to_unix_seconds(&reading(1970, 1, 1, 0, 0, 0)), 0);
+    }
+
+    #[test]
+    fn end_of_first_day() {
+        assert_eq!(to_unix_seconds(&reading(1970, 1, 1, 23, 59, 59)), 86399);
+    }
+
+    #[test]
+    fn second_day_starts_one_day_of_seconds_after_epoch() {
+        assert_eq!(to_unix_seconds(&reading(1970, 1, 2, 0, 0, 0)), 86400);
+    }
+
+    #[test]
+    fn month_boundary_jan_to_feb() {
+        let jan_31 = to_unix_seconds(&reading(1970, 1, 31, 0, 0, 0));
+        let feb_1 = to_unix_seconds(&reading(1970, 2, 1, 0, 0, 0));
+        assert_eq!(feb_1 - jan_31, 86400);
+    }
+
+    #[test]
+    fn century_leap_year_2000_has_feb_29() {
+        let feb_28 = to_unix_seconds(&reading(2000, 2, 28, 0, 0, 0));
+        let feb_29 = to_unix_seconds(&reading(2000, 2, 29, 0, 0, 0));
+        let mar_1 = to_unix_seconds(&reading(2000, 3, 1, 0, 0, 0));
+        assert_eq!(feb_29 - feb_28, 86400);
+        assert_eq!(mar_1 - feb_29, 86400);
+    }
+
+    #[test]
+    fn is_leap_year_follows_the_gregorian_rule() {
+        assert!(is_leap_year(2000));  // divisible by 400
+        assert!(!is_leap_year(1900)); // divisible by 100 but not 400
+        assert!(!is_leap_year(2100)); // divisible by 100 but not 400
+        assert!(is_leap_year(2004));  // divisible by 4, not by 100
+        assert!(!is_leap_year(2001)); // not divisible by 4
+    }
+}